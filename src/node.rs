@@ -1,13 +1,17 @@
 use embedded_can::{nb::Can, Frame, Id, StandardId};
 
 use crate::object_directory::ObjectDirectory;
-use crate::pdo::PdoObjects;
+use crate::pdo::{PdoFrameKind, PdoObjects};
 use crate::prelude::*;
 use crate::sdo_server::SdoState;
 use crate::sdo_server::SdoState::Normal;
+use crate::transport;
 use crate::util::get_cob_id;
 use crate::info;
 
+#[cfg(feature = "async")]
+pub mod async_driver;
+
 const DEFAULT_BLOCK_SIZE: u8 = 0x7F;
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -50,6 +54,10 @@ pub struct Node<CAN> where CAN: Can, CAN::Frame: Frame + Debug {
     pub(crate) sync_count: u32,
     pub(crate) event_count: u32,
     pub(crate) state: NodeState,
+
+    // The frame kind PDOs are packed for; `Classic` unless the transport
+    // supports CAN FD and the application opts in via `set_pdo_frame_kind`.
+    pub(crate) pdo_frame_kind: PdoFrameKind,
 }
 
 impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
@@ -80,9 +88,17 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
             sync_count: 0,
             event_count: 0,
             state: NodeState::Init,
+            pdo_frame_kind: PdoFrameKind::Classic,
         }
     }
 
+    /// Selects the frame kind outgoing PDOs are packed for. Use `Fd` once
+    /// the underlying transport is CAN FD capable, to allow mappings wider
+    /// than a classic 8-byte frame.
+    pub fn set_pdo_frame_kind(&mut self, kind: PdoFrameKind) {
+        self.pdo_frame_kind = kind;
+    }
+
     pub(crate) fn filter_frame(&self, frame: &CAN::Frame) -> bool {
         if let Some(cob_id) = get_cob_id(frame) {
             if cob_id & 0x7F == self.node_id {
@@ -145,6 +161,7 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
     }
 
     fn process_sync_frame(&mut self) -> Option<CAN::Frame> {
+        self.apply_buffered_rpdos();
         if self.state == NodeState::Operational {
             self.sync_count += 1;
             self.transmit_pdo_messages(true, NodeEvent::Unused, self.sync_count);
@@ -166,35 +183,29 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
         }
     }
 
-    pub fn communication_object_dispatch(&mut self, frame: CAN::Frame) -> Option<CAN::Frame> {
-        let cob_id = get_cob_id(&frame).unwrap();
+    pub fn communication_object_dispatch(&mut self, frame: &CAN::Frame) -> Option<CAN::Frame> {
+        let cob_id = get_cob_id(frame).unwrap();
         match cob_id & 0xFF80 {
-            0x000 => self.process_nmt_frame(&frame),
+            0x000 => self.process_nmt_frame(frame),
             0x080 => self.process_sync_frame(),
-            0x600 => self.dispatch_sdo_request(&frame),
-            _ => None,
+            0x600 => self.dispatch_sdo_request(frame),
+            _ => self.process_rpdo_frame(frame),
         }
     }
 
+    // `communication_object_dispatch` replies with a zero standard id to mean
+    // "nothing to send back"; both the blocking and async run loops need to
+    // recognize that before handing the frame to the transport.
+    pub(crate) fn is_empty_reply(frame: &CAN::Frame) -> bool {
+        matches!(frame.id(), Id::Standard(sid) if sid.as_raw() == 0)
+    }
+
     pub fn init(&mut self) {
         let ready_frame = Frame::new(StandardId::new(0x234).unwrap(), &[1, 2, 3, 5]).expect("");
-        self.can_network
-            .transmit(&ready_frame)
-            .expect("Failed to send CAN frame");
+        transport::send(&mut self.can_network, &ready_frame, transport::SendPolicy::ConfirmWithRetry {
+            max_retries: transport::DEFAULT_MAX_RETRIES,
+        });
     }
-    //
-    // fn transmit(&mut self, frame: &CAN::Frame, max_retries: i32) {
-    //     for _ in 1..max_retries {
-    //         match self.can_network.transmit(frame) {
-    //             Ok(None) => return,
-    //             Ok(Option::Some(f)) => self.transmit(&f, max_retries),
-    //             Err(err) => {
-    //                 info!("xfguo: Errors({:?}) in transmit frame, retry", err);
-    //             }
-    //         }
-    //     }
-    //     info!("xfguo: Failed to transmit frame {:?} after {:?} retries", frame, max_retries);
-    // }
 
     // Need to be non-blocking.
     pub fn process_one_frame(&mut self) {
@@ -208,17 +219,15 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
         };
         info!("[node] got frame: {:?}", frame);
 
-        if let Some(response) = self.communication_object_dispatch(frame) {
-            if let Id::Standard(sid) = response.id() {
-                if sid.as_raw() == 0 {
-                    // Don't need to send any reply for empty frame.
-                    return;
-                }
+        if let Some(response) = self.communication_object_dispatch(&frame) {
+            if Self::is_empty_reply(&response) {
+                // Don't need to send any reply for empty frame.
+                return;
             }
             // info!("[node] to send reply : {:?}", response);
-            self.can_network
-                .transmit(&response)
-                .expect("Failed to send CAN frame");
+            transport::send(&mut self.can_network, &response, transport::SendPolicy::ConfirmWithRetry {
+                max_retries: transport::DEFAULT_MAX_RETRIES,
+            });
             info!("[node] sent a frame : {:?}", response);
         }
     }
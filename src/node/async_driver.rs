@@ -0,0 +1,153 @@
+//! Async, executor-driven run loop for [`Node`].
+//!
+//! The `nb`-based API in [`crate::node`] requires a caller to spin
+//! [`Node::process_one_frame`] and drive [`Node::event_timer_callback`] /
+//! SYNC reception from a separate timer interrupt. This module adds an
+//! alternative for embassy-style (or any other `async` no_std) executors:
+//! `.await` the next incoming frame, SYNC tick, or event-timer tick instead
+//! of busy-polling, so a user can just `spawn(node.run(can, sync, event))`.
+//!
+//! Enabled with the `async` cargo feature; the `nb` API keeps working
+//! unchanged for bare-metal callers who don't want an executor.
+
+use core::fmt::Debug;
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::Poll;
+
+use embedded_can::Frame;
+
+use crate::info;
+use crate::node::Node;
+
+/// An async CAN transport, mirroring [`embedded_can::nb::Can`] but
+/// `.await`-based instead of non-blocking-poll based.
+pub trait AsyncCan {
+    type Frame: Frame + Debug;
+    type Error: Debug;
+
+    /// Waits for the next incoming frame.
+    async fn receive(&mut self) -> Result<Self::Frame, Self::Error>;
+
+    /// Sends `frame`, waiting for bus access if needed.
+    async fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error>;
+}
+
+/// A periodic tick source — a SYNC producer or the event timer — fed to
+/// [`Node::run`] so the run loop can `.await` timer expiry instead of being
+/// driven by an external callback.
+pub trait Ticker {
+    /// Waits for the next tick.
+    async fn tick(&mut self);
+}
+
+enum WakeReason<F> {
+    Frame(F),
+    Sync,
+    Event,
+    // A bus-off/overrun-style receive error. Surfaced as its own variant
+    // (rather than swallowed inside the `recv` branch) so that branch always
+    // resolves to `Ready` on its very first completion and `poll_fn` returns
+    // immediately — polling an `async fn` again after it has completed
+    // panics, so no branch here may ever be polled past the poll that
+    // resolves it.
+    RecvError,
+}
+
+async fn next_wake<C, S, E>(can: &mut C, sync_ticker: &mut S, event_ticker: &mut E) -> WakeReason<C::Frame>
+where
+    C: AsyncCan,
+    S: Ticker,
+    E: Ticker,
+{
+    let recv = async {
+        match can.receive().await {
+            Ok(frame) => WakeReason::Frame(frame),
+            Err(err) => {
+                info!("[node] async receive error, {:?}", err);
+                WakeReason::RecvError
+            }
+        }
+    };
+    let sync_tick = async {
+        sync_ticker.tick().await;
+        WakeReason::Sync
+    };
+    let event_tick = async {
+        event_ticker.tick().await;
+        WakeReason::Event
+    };
+
+    let mut recv = pin!(recv);
+    let mut sync_tick = pin!(sync_tick);
+    let mut event_tick = pin!(event_tick);
+
+    // A minimal `select3`: poll every branch each time we're woken and take
+    // whichever one is ready first, without pulling in an executor-specific
+    // select macro. Each branch now always resolves to a `WakeReason` (never
+    // `None`), so the first `Ready` ends this call and none of the pinned
+    // futures are polled again afterwards.
+    poll_fn(move |cx| {
+        if let Poll::Ready(reason) = recv.as_mut().poll(cx) {
+            return Poll::Ready(reason);
+        }
+        if let Poll::Ready(reason) = sync_tick.as_mut().poll(cx) {
+            return Poll::Ready(reason);
+        }
+        if let Poll::Ready(reason) = event_tick.as_mut().poll(cx) {
+            return Poll::Ready(reason);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+impl<CAN: embedded_can::nb::Can> Node<CAN>
+where
+    CAN::Frame: Frame + Debug,
+{
+    /// Runs the node forever on an async executor.
+    ///
+    /// `can` is the async transport frames are received from and replies are
+    /// sent on; `sync_ticker` / `event_ticker` drive `sync_count` /
+    /// `event_count` the same way the SYNC bus message and the event timer
+    /// do for the `nb` loop. Typical usage: `spawn(node.run(can, sync, event))`.
+    pub async fn run<C, S, E>(&mut self, mut can: C, mut sync_ticker: S, mut event_ticker: E) -> !
+    where
+        C: AsyncCan<Frame = CAN::Frame>,
+        S: Ticker,
+        E: Ticker,
+    {
+        loop {
+            match next_wake(&mut can, &mut sync_ticker, &mut event_ticker).await {
+                WakeReason::Frame(frame) => {
+                    // No `filter_frame` call here, matching `process_one_frame`:
+                    // it would drop NMT/SYNC broadcasts (node portion 0) and any
+                    // RPDO not addressed to this node_id, neither of which this
+                    // node's `communication_object_dispatch` wants filtered out.
+                    info!("[node] got frame: {:?}", frame);
+                    if let Some(response) = self.communication_object_dispatch(&frame) {
+                        if Self::is_empty_reply(&response) {
+                            continue;
+                        }
+                        // Every reply `communication_object_dispatch` produces
+                        // today is an SDO response, so it gets the same
+                        // confirm-with-retry policy `process_one_frame` uses.
+                        crate::transport::r#async::send_with_retry(
+                            &mut can,
+                            &response,
+                            crate::transport::DEFAULT_MAX_RETRIES,
+                        ).await;
+                    }
+                }
+                WakeReason::Sync => {
+                    self.process_sync_frame();
+                }
+                WakeReason::Event => {
+                    self.event_timer_callback();
+                }
+                WakeReason::RecvError => continue,
+            }
+        }
+    }
+}
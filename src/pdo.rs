@@ -10,6 +10,7 @@ use crate::error::CanAbortCode;
 use crate::info;
 use crate::node::{Node, NodeEvent};
 use crate::object_directory::{ObjectDirectory, Variable};
+use crate::util::get_cob_id;
 
 #[derive(Debug, Clone, Copy)]
 pub enum PdoType {
@@ -17,6 +18,25 @@ pub enum PdoType {
     RPDO,
 }
 
+/// The CAN frame a PDO is packed for, and therefore the MTU a mapping's
+/// total bit width must fit within.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdoFrameKind {
+    /// Classic CAN: up to 8 data bytes.
+    Classic,
+    /// CAN FD: up to 64 data bytes.
+    Fd,
+}
+
+impl PdoFrameKind {
+    pub fn max_bytes(self) -> usize {
+        match self {
+            PdoFrameKind::Classic => 8,
+            PdoFrameKind::Fd => 64,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PdoObject {
     pub pdo_type: PdoType,
@@ -36,6 +56,10 @@ pub struct PdoObject {
     // Mapping relative
     pub num_of_map_objs: u8,
     pub mappings: Vec<(u16, u8, u8)>,  // index, sub_index, length
+
+    // RPDO reception only: the latest frame received for a synchronous
+    // (transmission_type 0-240) RPDO, buffered until the next SYNC.
+    pub pending_rpdo_frame: Option<Vec<u8>>,
 }
 
 pub struct PdoObjects {
@@ -99,6 +123,7 @@ impl PdoObjects {
             event_timer: 0,
             num_of_map_objs: 0,
             mappings: vec![],
+            pending_rpdo_frame: None,
         };
         let default_tpdo = PdoObject {
             pdo_type: PdoType::TPDO,
@@ -112,6 +137,7 @@ impl PdoObjects {
             event_timer: 0,
             num_of_map_objs: 0,
             mappings: vec![],
+            pending_rpdo_frame: None,
         };
 
         let rpdos = [(); 4].map(|_| default_rpdo.clone());
@@ -136,6 +162,7 @@ impl PdoObjects {
             }
         }
 
+        res.rebuild_cob_to_index();
         res
     }
 
@@ -149,6 +176,48 @@ impl PdoObjects {
             0x1A => self.tpdos[x].update_map_params(var),
             _ => {}
         }
+        self.rebuild_cob_to_index();
+    }
+
+    // Keyed by COB-ID so an incoming frame can be matched straight back to
+    // the RPDO slot that consumes it, instead of scanning all 4 slots on
+    // every received frame.
+    fn rebuild_cob_to_index(&mut self) {
+        self.cob_to_index.clear();
+        for (i, rpdo) in self.rpdos.iter().enumerate() {
+            if rpdo.is_pdo_valid {
+                self.cob_to_index.insert(rpdo.cob_id as u32, i);
+            }
+        }
+    }
+
+    /// Looks up the RPDO `cob_id` addresses and decides what to do with
+    /// `data`: for asynchronous transmission types (0xFE/0xFF) it's handed
+    /// straight back for immediate application; for synchronous types
+    /// (0-240) it's buffered in that slot's `pending_rpdo_frame` and this
+    /// returns `None`, so the caller applies it later via
+    /// `drain_buffered_rpdos` on the next SYNC. Returns `None` if `cob_id`
+    /// doesn't match a valid RPDO.
+    pub(crate) fn receive_rpdo_frame(&mut self, cob_id: u32, data: Vec<u8>) -> Option<(usize, Vec<u8>)> {
+        let idx = *self.cob_to_index.get(&cob_id)?;
+        let rpdo = &self.rpdos[idx];
+        if !rpdo.is_pdo_valid {
+            return None;
+        }
+        if rpdo.transmission_type == 0xFE || rpdo.transmission_type == 0xFF {
+            Some((idx, data))
+        } else {
+            self.rpdos[idx].pending_rpdo_frame = Some(data);
+            None
+        }
+    }
+
+    /// Drains every RPDO slot's frame buffered by `receive_rpdo_frame` since
+    /// the last call, for the caller to apply on SYNC.
+    pub(crate) fn drain_buffered_rpdos(&mut self) -> Vec<(usize, Vec<u8>)> {
+        (0..self.rpdos.len())
+            .filter_map(|i| self.rpdos[i].pending_rpdo_frame.take().map(|data| (i, data)))
+            .collect()
     }
 }
 
@@ -174,7 +243,50 @@ fn should_trigger_pdo(is_sync: bool, event: NodeEvent, transmission_type: u32, e
     true
 }
 
+// Splits a received RPDO payload back into its mapped `(index, sub_index,
+// value)` fields, using the same per-field bit widths `gen_pdo_frame` packed
+// it with.
+fn rpdo_fields(pdo: &PdoObject, data: &[u8]) -> Vec<(u16, u8, u64)> {
+    let bits: Vec<u8> = (1..=pdo.num_of_map_objs).map(|i| pdo.mappings[i as usize].2).collect();
+    unpack_data(data, &bits)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (value, _))| {
+            let (index, sub_index, _) = pdo.mappings[i + 1];
+            (index, sub_index, value)
+        })
+        .collect()
+}
+
 impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
+    // Looks up the RPDO a received frame's COB-ID belongs to and, if found,
+    // either applies it immediately (asynchronous transmission types 0xFE /
+    // 0xFF) or buffers it for the next SYNC (synchronous types 0-240).
+    pub(crate) fn process_rpdo_frame(&mut self, frame: &CAN::Frame) -> Option<CAN::Frame> {
+        let cob_id = get_cob_id(frame)? as u32;
+        let data = frame.data().to_vec();
+        if let Some((idx, data)) = self.pdo_objects.receive_rpdo_frame(cob_id, data) {
+            self.apply_rpdo_data(idx, &data);
+        }
+        None
+    }
+
+    // Applies every RPDO that buffered a frame since the last SYNC, writing
+    // each mapped field into the object directory.
+    pub(crate) fn apply_buffered_rpdos(&mut self) {
+        for (idx, data) in self.pdo_objects.drain_buffered_rpdos() {
+            self.apply_rpdo_data(idx, &data);
+        }
+    }
+
+    fn apply_rpdo_data(&mut self, idx: usize, data: &[u8]) {
+        for (index, sub_index, value) in rpdo_fields(&self.pdo_objects.rpdos[idx], data) {
+            if let Err(err) = self.object_directory.set_variable(index, sub_index, value) {
+                info!("Errors writing RPDO field {:#x}:{:#x}, err: {:?}", index, sub_index, err);
+            }
+        }
+    }
+
     // TODO(zephyr): Change type to Sync / Event.
     pub(crate) fn transmit_pdo_messages(&mut self, is_sync: bool, event: NodeEvent, count: u32) {
         // info!("xfguo: transmit_pdo_messages 0");
@@ -194,17 +306,13 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
 
             // info!("xfguo: transmit_pdo_messages 2, pdo[{}] = {:#x?}", i, pdo);
             // Emit a TPDO message.
-            match self.gen_pdo_frame(pdo.cob_id as u16, pdo.num_of_map_objs, pdo.mappings.clone()) {
+            let frame_kind = self.pdo_frame_kind;
+            match self.gen_pdo_frame(pdo.cob_id as u16, pdo.num_of_map_objs, pdo.mappings.clone(), frame_kind) {
                 Ok(f) => {
                     info!("xfguo: try to send tpdo packet: {:?}", f);
-                    match self.can_network.transmit(&f) {
-                        Err(err) => {
-                            info!("Errors in transmit TPDO frame, err: {:?}", err);
-                        }
-                        _ => {
-                            info!("xfguo: sent tpdo packet: {:?}", f);
-                        }
-                    }
+                    // TPDOs are fire-and-forget: a stale broadcast is better
+                    // dropped than retried.
+                    crate::transport::send(&mut self.can_network, &f, crate::transport::SendPolicy::BestEffort);
                 }
                 Err(err) => {
                     info!("Errors in generating PDO frame. err: {:?}", err);
@@ -213,7 +321,7 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
         }
     }
 
-    pub(crate) fn gen_pdo_frame(&mut self, cob_id: u16, num_of_map_objs: u8, mappings: Vec<(u16, u8, u8)>)
+    pub(crate) fn gen_pdo_frame(&mut self, cob_id: u16, num_of_map_objs: u8, mappings: Vec<(u16, u8, u8)>, frame_kind: PdoFrameKind)
                                 -> Result<CAN::Frame, CanAbortCode> {
         let mut t = Vec::new();
         // info!("xfguo: gen_pdo_frame() 0, {}, {:#x?}", num_of_map_objs, mappings);
@@ -228,8 +336,19 @@ impl<CAN: Can> Node<CAN> where CAN::Frame: Frame + Debug {
                 Err(_) => return Err(CanAbortCode::GeneralError),
             }
         }
+        let total_bits: usize = t.iter().map(|&(_, bits)| bits as usize).sum();
+        if total_bits > frame_kind.max_bytes() * 8 {
+            info!("Mapping needs {} bits, which doesn't fit a {:?} frame", total_bits, frame_kind);
+            return Err(CanAbortCode::GeneralError);
+        }
         let packet = pack_data(&t);
-        Ok(CAN::Frame::new(StandardId::new(cob_id).unwrap(), packet.as_slice()).unwrap())
+        CAN::Frame::new(StandardId::new(cob_id).unwrap(), packet.as_slice()).ok_or_else(|| {
+            // A classic `Frame` impl can't construct a frame longer than 8
+            // bytes; the `total_bits` check above only catches that for
+            // `PdoFrameKind::Classic`, since `Fd` itself allows up to 64.
+            info!("Frame impl rejected a {}-byte PDO payload", packet.len());
+            CanAbortCode::GeneralError
+        })
     }
 }
 
@@ -241,38 +360,55 @@ fn vec_to_u64(v: &Vec<u8>) -> u64 {
     res
 }
 
-fn pack_data(vec: &Vec<(u64, u8)>) -> Vec<u8> {
-    let mut merged = 0u64;
-    let mut total_bits = 0u8;
-    for (data, bits) in vec {
-        total_bits += bits;
-        // TODO(zephyr): optimize the expr below
-        merged = (merged << bits) | (data & ((1 << bits) - 1));
+// Writes the low `bits` bits of `value`, most-significant-bit first, into
+// `out` starting at `*cursor`, and advances `*cursor` past them. Used
+// instead of a single `u64` accumulator so mapped fields can add up to more
+// than 64 bits total (e.g. a CAN FD frame's 64 bytes).
+fn write_bits(out: &mut [u8], cursor: &mut usize, value: u64, bits: u8) {
+    for i in (0..bits).rev() {
+        let bit = (value >> i) & 1;
+        if bit != 0 {
+            let (byte_idx, bit_idx) = (*cursor / 8, 7 - *cursor % 8);
+            out[byte_idx] |= 1 << bit_idx;
+        }
+        *cursor += 1;
     }
-    let total_bytes = total_bits / 8 + if total_bits % 8 > 0 { 1 } else { 0 };
-    let mut res = vec![0u8; total_bytes as usize];
-    for i in 0..total_bytes {
-        res[(total_bytes - 1 - i) as usize] = (merged & 0xFF) as u8;
-        merged = merged >> 8;
+}
+
+// Inverse of `write_bits`: reads `bits` bits starting at `*cursor`.
+fn read_bits(data: &[u8], cursor: &mut usize, bits: u8) -> u64 {
+    let mut value = 0u64;
+    for _ in 0..bits {
+        let (byte_idx, bit_idx) = (*cursor / 8, 7 - *cursor % 8);
+        let bit = (data.get(byte_idx).copied().unwrap_or(0) >> bit_idx) & 1;
+        value = (value << 1) | bit as u64;
+        *cursor += 1;
     }
-    res
+    value
 }
 
-fn unpack_data(vec: &Vec<u8>, bits: &Vec<u8>) -> Vec<(u64, u8)> {
-    let mut data = vec_to_u64(vec);
-    println!("{:#x}", data);
-    let len = bits.len();
-    let mut res = vec![(0u64, 0u8); len];
-    for i in 0..len {
-        let idx = len - 1 - i;
-        let t = data & ((1 << bits[idx]) - 1);
-        data = data >> bits[idx];
-        res[idx] = (t, bits[idx]);
-        println!("{:#x}, {:#x}, ", t, data);
+// The old `u64` accumulator right-justified the concatenated fields inside
+// the output bytes (any slack from a non-byte-aligned mapping ended up as
+// zero bits at the *front* of byte 0, not at the end of the last byte).
+// Starting the cursor at the padding width instead of 0 reproduces that
+// exact layout while still supporting more than 64 bits of fields.
+fn pack_data(vec: &Vec<(u64, u8)>) -> Vec<u8> {
+    let total_bits: usize = vec.iter().map(|&(_, bits)| bits as usize).sum();
+    let total_bytes = (total_bits + 7) / 8;
+    let mut res = vec![0u8; total_bytes];
+    let mut cursor = total_bytes * 8 - total_bits;
+    for &(data, bits) in vec {
+        write_bits(&mut res, &mut cursor, data, bits);
     }
     res
 }
 
+fn unpack_data(vec: &[u8], bits: &Vec<u8>) -> Vec<(u64, u8)> {
+    let total_bits: usize = bits.iter().map(|&b| b as usize).sum();
+    let mut cursor = vec.len() * 8 - total_bits;
+    bits.iter().map(|&bits| (read_bits(vec, &mut cursor, bits), bits)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,4 +438,142 @@ mod tests {
         let cutted_data = cut_data_with_bits(&initial_data);
         assert_eq!(result_data, cutted_data);
     }
+
+    #[test]
+    fn test_pack_data_matches_byte_aligned_legacy_output() {
+        // A byte-aligned mapping (the common case: mapped objects fill
+        // whole bytes) packed identically before and after the bit-cursor
+        // rework, so this is a regression test against today's output.
+        let fields = vec![(0xABu64, 8), (0xCDu64, 8), (0x12u64, 8)];
+        assert_eq!(pack_data(&fields), vec![0xAB, 0xCD, 0x12]);
+    }
+
+    #[test]
+    fn test_pack_data_matches_legacy_output_for_non_byte_aligned_fields() {
+        // Same field widths as `test_data_to_packet_and_packet_to_data`,
+        // with concrete values, checked against the bytes the old `u64`
+        // accumulator produced for this exact input.
+        let fields = vec![(0xABCu64, 12), (0x123456u64, 20), (0x102u64, 9)];
+        assert_eq!(pack_data(&fields), vec![0x01, 0x57, 0x84, 0x68, 0xad, 0x02]);
+    }
+
+    #[test]
+    fn test_pack_data_supports_more_than_64_bits() {
+        // Would have silently overflowed the old u64 accumulator.
+        let fields = vec![(0xAAAAAAAAAAAAAAAAu64, 64), (0xFFu64, 8)];
+        let packet = pack_data(&fields);
+        assert_eq!(packet.len(), 9);
+        assert_eq!(unpack_data(&packet, &vec![64, 8]), fields);
+    }
+
+    #[test]
+    fn test_rpdo_fields_round_trips_a_tpdo_packet() {
+        // Same packing `gen_pdo_frame` produces for a TPDO: field values and
+        // their bit widths, packed with `pack_data`.
+        let fields = vec![(0x11u64, 8), (0x2233u64, 16), (0x1u64, 8)];
+        let packet = pack_data(&fields);
+
+        let mut rpdo = PdoObject {
+            pdo_type: PdoType::RPDO,
+            is_pdo_valid: true,
+            _not_used_rtr_allowed: false,
+            _not_used_is_29bit_can_id: false,
+            largest_sub_index: 5,
+            cob_id: 0x202,
+            transmission_type: 0xFF,
+            inhibit_time: 0,
+            event_timer: 0,
+            num_of_map_objs: 3,
+            mappings: vec![(0, 0, 0)],
+            pending_rpdo_frame: None,
+        };
+        rpdo.mappings.push((0x6000, 1, 8));
+        rpdo.mappings.push((0x6001, 1, 16));
+        rpdo.mappings.push((0x6002, 1, 8));
+
+        let decoded = rpdo_fields(&rpdo, &packet);
+        assert_eq!(decoded, vec![
+            (0x6000, 1, 0x11),
+            (0x6001, 1, 0x2233),
+            (0x6002, 1, 0x1),
+        ]);
+    }
+
+    fn blank_pdo_object(pdo_type: PdoType) -> PdoObject {
+        PdoObject {
+            pdo_type,
+            is_pdo_valid: false,
+            _not_used_rtr_allowed: false,
+            _not_used_is_29bit_can_id: false,
+            largest_sub_index: 5,
+            cob_id: 0,
+            transmission_type: 0x01,
+            inhibit_time: 0,
+            event_timer: 0,
+            num_of_map_objs: 0,
+            mappings: vec![],
+            pending_rpdo_frame: None,
+        }
+    }
+
+    // A `PdoObjects` hosting a single valid RPDO at slot 0, with
+    // `cob_to_index` populated the way `rebuild_cob_to_index` would.
+    fn pdo_objects_with_rpdo(rpdo: PdoObject) -> PdoObjects {
+        let mut cob_to_index = HashMap::new();
+        cob_to_index.insert(rpdo.cob_id as u32, 0);
+        let rpdos = [rpdo, blank_pdo_object(PdoType::RPDO), blank_pdo_object(PdoType::RPDO), blank_pdo_object(PdoType::RPDO)];
+        let tpdos = [(); 4].map(|_| blank_pdo_object(PdoType::TPDO));
+        PdoObjects { rpdos, tpdos, cob_to_index }
+    }
+
+    #[test]
+    fn test_receive_rpdo_frame_buffers_synchronous_types_until_sync() {
+        let mut rpdo = blank_pdo_object(PdoType::RPDO);
+        rpdo.is_pdo_valid = true;
+        rpdo.cob_id = 0x202;
+        rpdo.transmission_type = 1; // synchronous
+        rpdo.num_of_map_objs = 2;
+        rpdo.mappings = vec![(0, 0, 0), (0x6000, 1, 8), (0x6001, 1, 16)];
+        let packet = pack_data(&vec![(0x11u64, 8), (0x2233u64, 16)]);
+        let mut pdo_objects = pdo_objects_with_rpdo(rpdo.clone());
+
+        // A synchronous RPDO is buffered, not applied, when it arrives.
+        assert_eq!(pdo_objects.receive_rpdo_frame(0x202, packet.clone()), None);
+        assert_eq!(pdo_objects.rpdos[0].pending_rpdo_frame, Some(packet.clone()));
+
+        // It's only handed back for application once SYNC drains it.
+        let drained = pdo_objects.drain_buffered_rpdos();
+        assert_eq!(drained, vec![(0, packet.clone())]);
+        assert!(pdo_objects.rpdos[0].pending_rpdo_frame.is_none());
+
+        // And it decodes to the fields `gen_pdo_frame`'s packing would have
+        // produced from the same mapping.
+        assert_eq!(rpdo_fields(&rpdo, &packet), vec![(0x6000, 1, 0x11), (0x6001, 1, 0x2233)]);
+    }
+
+    #[test]
+    fn test_receive_rpdo_frame_applies_asynchronous_types_immediately() {
+        let mut rpdo = blank_pdo_object(PdoType::RPDO);
+        rpdo.is_pdo_valid = true;
+        rpdo.cob_id = 0x302;
+        rpdo.transmission_type = 0xFF; // asynchronous
+        rpdo.num_of_map_objs = 1;
+        rpdo.mappings = vec![(0, 0, 0), (0x6010, 1, 8)];
+        let packet = pack_data(&vec![(0x42u64, 8)]);
+        let mut pdo_objects = pdo_objects_with_rpdo(rpdo);
+
+        assert_eq!(pdo_objects.receive_rpdo_frame(0x302, packet.clone()), Some((0, packet)));
+        assert!(pdo_objects.rpdos[0].pending_rpdo_frame.is_none());
+    }
+
+    #[test]
+    fn test_receive_rpdo_frame_ignores_unmapped_cob_id() {
+        let mut rpdo = blank_pdo_object(PdoType::RPDO);
+        rpdo.is_pdo_valid = true;
+        rpdo.cob_id = 0x202;
+        let mut pdo_objects = pdo_objects_with_rpdo(rpdo);
+
+        assert_eq!(pdo_objects.receive_rpdo_frame(0xDEAD, vec![0; 4]), None);
+        assert!(pdo_objects.drain_buffered_rpdos().is_empty());
+    }
 }
\ No newline at end of file
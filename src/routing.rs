@@ -0,0 +1,155 @@
+//! Multi-node routing for CANopen gateway / manager deployments.
+//!
+//! A bare [`Node`] is bound to a single `node_id` and a single CAN
+//! interface; [`Node::filter_frame`] just drops anything not addressed to
+//! it. [`Gateway`] sits in front of one or more [`Node`]s and one or more
+//! CAN segments, and uses a [`RoutingTable`] to decide, per destination
+//! node-id, whether a frame is handled by a locally-hosted `Node` or
+//! forwarded onto another segment — turning the crate from a single-device
+//! stack into a CANopen-to-CANopen bridge or a manager hosting several
+//! virtual nodes.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use embedded_can::{nb::Can, Frame};
+
+use crate::info;
+use crate::node::Node;
+use crate::transport::{self, SendPolicy};
+use crate::util::get_cob_id;
+
+/// The CANopen node-id space is 1-127; index 0 is unused but kept so a
+/// node-id can index the table directly.
+pub const DEST_COUNT: usize = 128;
+
+// Replies from hosted nodes are SDO responses, sent with the same
+// confirm-with-retry policy `Node::process_one_frame` uses.
+const REPLY_POLICY: SendPolicy = SendPolicy::ConfirmWithRetry { max_retries: transport::DEFAULT_MAX_RETRIES };
+
+/// Where a frame addressed to a given node-id should go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// Handled by a `Node` hosted locally by the `Gateway`.
+    Local,
+    /// Forwarded onto the segment at this index in `Gateway::segments`.
+    Forward(usize),
+}
+
+/// Maps each destination node-id to a [`Route`]. Defaults to `Local` for
+/// every id, i.e. an all-local topology, and is reconfigurable at runtime
+/// via [`RoutingTable::set`].
+pub struct RoutingTable {
+    routes: [Route; DEST_COUNT],
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        RoutingTable { routes: [Route::Local; DEST_COUNT] }
+    }
+
+    pub fn set(&mut self, node_id: u8, route: Route) {
+        self.routes[node_id as usize] = route;
+    }
+
+    pub fn get(&self, node_id: u8) -> Route {
+        self.routes[node_id as usize]
+    }
+}
+
+impl Default for RoutingTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Owns several [`Node`]s and/or CAN segments and dispatches each received
+/// frame by its COB-ID's node-id portion through a [`RoutingTable`]:
+/// locally-hosted nodes are driven directly, and frames destined for
+/// another segment are forwarded there.
+pub struct Gateway<CAN> where CAN: Can, CAN::Frame: Frame + Debug {
+    nodes: Vec<Node<CAN>>,
+    segments: Vec<CAN>,
+    table: RoutingTable,
+}
+
+impl<CAN: Can> Gateway<CAN> where CAN::Frame: Frame + Debug {
+    pub fn new() -> Self {
+        Gateway { nodes: Vec::new(), segments: Vec::new(), table: RoutingTable::new() }
+    }
+
+    /// Hosts `node` locally. Its `node_id` is looked up on every frame
+    /// routed as [`Route::Local`].
+    pub fn add_node(&mut self, node: Node<CAN>) {
+        self.nodes.push(node);
+    }
+
+    /// Adds a CAN segment frames can be forwarded onto, returning its index
+    /// for use with [`Route::Forward`].
+    pub fn add_segment(&mut self, can: CAN) -> usize {
+        self.segments.push(can);
+        self.segments.len() - 1
+    }
+
+    /// Sets the route for `node_id`.
+    pub fn route(&mut self, node_id: u8, route: Route) {
+        self.table.set(node_id, route);
+    }
+
+    /// Handles a frame received on `segment_index`: dispatches it to the
+    /// locally-hosted node it addresses, or forwards it to the segment its
+    /// route points at.
+    pub fn on_frame(&mut self, segment_index: usize, frame: CAN::Frame) {
+        let Some(cob_id) = get_cob_id(&frame) else { return; };
+        let node_id = (cob_id & 0x7F) as u8;
+        if node_id == 0 {
+            // NMT/SYNC broadcasts address every node, not a single routed
+            // one: fan them out to every locally-hosted node instead of
+            // looking them up in the routing table, which only maps
+            // individual node-ids.
+            for i in 0..self.nodes.len() {
+                if let Some(reply) = self.nodes[i].communication_object_dispatch(&frame) {
+                    if !Node::<CAN>::is_empty_reply(&reply) {
+                        self.send_on(segment_index, &reply, REPLY_POLICY);
+                    }
+                }
+            }
+            return;
+        }
+        match self.table.get(node_id) {
+            Route::Local => {
+                let Some(node) = self.nodes.iter_mut().find(|n| n.node_id == node_id as u16) else {
+                    info!("[gateway] node {} is routed locally but not hosted here, dropping frame", node_id);
+                    return;
+                };
+                if let Some(reply) = node.communication_object_dispatch(&frame) {
+                    // `communication_object_dispatch` replies with a
+                    // zero-standard-id sentinel to mean "nothing to send
+                    // back"; forwarding that onto the bus would be a bogus
+                    // frame.
+                    if !Node::<CAN>::is_empty_reply(&reply) {
+                        self.send_on(segment_index, &reply, REPLY_POLICY);
+                    }
+                }
+            }
+            Route::Forward(segment) => {
+                self.send_on(segment, &frame, SendPolicy::BestEffort);
+            }
+        }
+    }
+
+    fn send_on(&mut self, segment_index: usize, frame: &CAN::Frame, policy: SendPolicy) {
+        match self.segments.get_mut(segment_index) {
+            Some(can) => {
+                transport::send(can, frame, policy);
+            }
+            None => info!("[gateway] no segment {}, dropping frame", segment_index),
+        }
+    }
+}
+
+impl<CAN: Can> Default for Gateway<CAN> where CAN::Frame: Frame + Debug {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,118 @@
+//! Sending policies for outgoing CAN frames.
+//!
+//! Every emission site used to call `can_network.transmit(&f)` directly and
+//! either `.expect()` on the result or log-and-drop the error, and the
+//! commented-out `Node::transmit` draft sketched out a retrying sender that
+//! never got wired in. This module makes that choice explicit and lets
+//! callers pick a policy per message class: SDO replies (and the `init`
+//! ready frame) need confirmation, so they retry; TPDOs are fire-and-forget,
+//! so a stale broadcast is dropped rather than retried.
+
+use core::fmt::Debug;
+
+use embedded_can::nb::Can;
+use embedded_can::Frame;
+
+use crate::info;
+
+/// Default retry budget for [`SendPolicy::ConfirmWithRetry`] when a call
+/// site doesn't have a more specific value in mind.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// How a frame should be handed to the CAN peripheral.
+#[derive(Debug, Clone, Copy)]
+pub enum SendPolicy {
+    /// Retry up to `max_retries` times. If the mailbox evicts an
+    /// already-queued frame to make room (`Ok(Some(evicted))`), that frame
+    /// is resent before reporting success for the original one.
+    ConfirmWithRetry { max_retries: u32 },
+    /// Try once; log and drop the frame on error instead of retrying.
+    BestEffort,
+}
+
+/// Sends `frame` according to `policy`. Returns `true` if the frame (and any
+/// frame it evicted from the mailbox) was accepted by the peripheral.
+pub fn send<CAN>(can: &mut CAN, frame: &CAN::Frame, policy: SendPolicy) -> bool
+where
+    CAN: Can,
+    CAN::Frame: Frame + Debug,
+    CAN::Error: Debug,
+{
+    match policy {
+        SendPolicy::ConfirmWithRetry { max_retries } => send_with_retry(can, frame, max_retries),
+        SendPolicy::BestEffort => send_best_effort(can, frame),
+    }
+}
+
+fn send_with_retry<CAN>(can: &mut CAN, frame: &CAN::Frame, max_retries: u32) -> bool
+where
+    CAN: Can,
+    CAN::Frame: Frame + Debug,
+    CAN::Error: Debug,
+{
+    // One initial attempt plus up to `max_retries` retries.
+    for _ in 0..=max_retries {
+        match can.transmit(frame) {
+            Ok(None) => return true,
+            Ok(Some(evicted)) => {
+                return send_with_retry(can, &evicted, max_retries);
+            }
+            Err(nb::Error::WouldBlock) => continue,
+            Err(nb::Error::Other(err)) => {
+                info!("[transport] error sending frame, retrying: {:?}", err);
+            }
+        }
+    }
+    info!("[transport] gave up sending frame {:?} after {} retries", frame, max_retries);
+    false
+}
+
+fn send_best_effort<CAN>(can: &mut CAN, frame: &CAN::Frame) -> bool
+where
+    CAN: Can,
+    CAN::Frame: Frame + Debug,
+    CAN::Error: Debug,
+{
+    match can.transmit(frame) {
+        Ok(_) => true,
+        Err(err) => {
+            info!("[transport] dropping frame after error, {:?}", err);
+            false
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub mod r#async {
+    //! The async counterparts of [`super::SendPolicy`], for the
+    //! executor-driven run loop in [`crate::node::async_driver`].
+
+    use super::*;
+    use crate::node::async_driver::AsyncCan;
+
+    /// Makes a single `transmit` attempt and returns once it completes;
+    /// errors are logged and the frame is dropped rather than retried.
+    pub async fn send_best_effort<C: AsyncCan>(can: &mut C, frame: &C::Frame) -> bool {
+        match can.transmit(frame).await {
+            Ok(()) => true,
+            Err(err) => {
+                info!("[transport] dropping frame after async error, {:?}", err);
+                false
+            }
+        }
+    }
+
+    /// Retries up to `max_retries` times before giving up. The async
+    /// transport's `transmit` doesn't report mailbox eviction like its `nb`
+    /// counterpart, so there's no evicted frame to re-queue here.
+    pub async fn send_with_retry<C: AsyncCan>(can: &mut C, frame: &C::Frame, max_retries: u32) -> bool {
+        for _ in 0..=max_retries {
+            match can.transmit(frame).await {
+                Ok(()) => return true,
+                Err(err) => info!("[transport] async send error, retrying: {:?}", err),
+            }
+        }
+        info!("[transport] gave up async send after {} retries", max_retries);
+        false
+    }
+}